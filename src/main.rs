@@ -1,9 +1,16 @@
-use std::{collections::HashSet, fs, process::ExitCode};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+    process::ExitCode,
+};
 
-use clap::Parser;
-use kurbo::BezPath;
+use clap::{Parser, ValueEnum};
+use kurbo::{BezPath, Line, ParamCurveNearest, PathEl, Point};
+use rayon::prelude::*;
 use regex::Regex;
-use skrifa::{FontRef, GlyphId, MetadataProvider, Tag, instance::Location, raw::TableProvider};
+use serde::Serialize;
+use skrifa::{instance::Location, raw::TableProvider, FontRef, GlyphId, MetadataProvider, Tag};
 use sleipnir::{
     draw_glyph::DrawOptions,
     icon2svg::draw_icon,
@@ -17,16 +24,15 @@ fn print_problems(desc: &str, offenders: &[Icon]) {
     }
 }
 
-trait PrintOnly {
-    fn print_only(&self, desc: &str, other: &Self) -> usize;
+trait OnlyIn {
+    fn only_in(&self, other: &Self) -> Vec<Icon>;
 }
 
-impl PrintOnly for HashSet<Icon> {
-    fn print_only(&self, desc: &str, other: &Self) -> usize {
-        let mut only = self.difference(&other).cloned().collect::<Vec<_>>();
+impl OnlyIn for HashSet<Icon> {
+    fn only_in(&self, other: &Self) -> Vec<Icon> {
+        let mut only = self.difference(other).cloned().collect::<Vec<_>>();
         only.sort_by_cached_key(|i| i.names.join(","));
-        print_problems(desc, &only);
-        only.len()
+        only
     }
 }
 
@@ -64,21 +70,41 @@ fn stops(min: i32, default: i32, max: i32, step: i32) -> Vec<i32> {
     stops
 }
 
+/// How many equal parts to split an axis we don't otherwise know how to step into; the
+/// `UNKNOWN_AXIS_SPLITS - 1` interior points are sampled in addition to min/default/max.
+const UNKNOWN_AXIS_SPLITS: i32 = 4;
+
+/// min, default, max plus an even N-way split, for axes with no known-good step size.
+fn even_split(min: i32, default: i32, max: i32) -> Vec<i32> {
+    let mut values = vec![min, default, max];
+    if max > min {
+        // Float step, rounded per-sample, so a range that doesn't divide evenly by
+        // UNKNOWN_AXIS_SPLITS still gets roughly uniform spacing instead of a truncated
+        // final gap.
+        let step = (max - min) as f64 / UNKNOWN_AXIS_SPLITS as f64;
+        values.extend((1..UNKNOWN_AXIS_SPLITS).map(|i| min + (step * i as f64).round() as i32));
+    }
+    values
+}
+
 impl Axis {
-    fn stops(&self) -> Vec<(Tag, i32)> {
+    /// Stops to sample this axis at. `overrides` take priority; failing that known Material
+    /// axes use their established step, and unknown axes fall back to [`even_split`].
+    fn stops(&self, overrides: &HashMap<Tag, i32>) -> Vec<(Tag, i32)> {
         const FILL_AXIS: Tag = Tag::new(b"FILL");
         const GRADE_AXIS: Tag = Tag::new(b"GRAD");
         const ROUND_AXIS: Tag = Tag::new(b"ROND");
         const OPSZ_AXIS: Tag = Tag::new(b"opsz");
         const WGHT_AXIS: Tag = Tag::new(b"wght");
 
-        let mut values = match self.tag {
-            FILL_AXIS => stops(self.min, self.default, self.max, 1),
-            GRADE_AXIS => stops(self.min, self.default, self.max, 25),
-            ROUND_AXIS => stops(self.min, self.default, self.max, 50),
-            OPSZ_AXIS => stops(self.min, self.default, self.max, 16),
-            WGHT_AXIS => stops(self.min, self.default, self.max, 200),
-            _ => panic!("What is {}?!", self.tag),
+        let mut values = match (overrides.get(&self.tag), self.tag) {
+            (Some(&step), _) => stops(self.min, self.default, self.max, step),
+            (None, FILL_AXIS) => stops(self.min, self.default, self.max, 1),
+            (None, GRADE_AXIS) => stops(self.min, self.default, self.max, 25),
+            (None, ROUND_AXIS) => stops(self.min, self.default, self.max, 50),
+            (None, OPSZ_AXIS) => stops(self.min, self.default, self.max, 16),
+            (None, WGHT_AXIS) => stops(self.min, self.default, self.max, 200),
+            (None, _) => even_split(self.min, self.default, self.max),
         };
         values.sort();
         values.dedup();
@@ -86,11 +112,20 @@ impl Axis {
     }
 }
 
-fn constellation(font: &FontRef<'_>) -> HashSet<Location> {
+/// Every `Location` in the font's design space Cartesian product, paired with the axis
+/// tag/value pairs (in user units) that produced it, so callers can render a human-readable
+/// label without re-deriving it from the normalized `Location`.
+fn constellation(
+    font: &FontRef<'_>,
+    axis_overrides: &HashMap<Tag, i32>,
+) -> Vec<(Location, Vec<(Tag, i32)>)> {
     let axes = axes(font);
-    let mut stop_lists = axes.iter().map(|a| a.stops()).collect::<Vec<_>>();
+    let mut stop_lists = axes
+        .iter()
+        .map(|a| a.stops(axis_overrides))
+        .collect::<Vec<_>>();
 
-    let mut raw_locations = vec![Vec::<(Tag, f32)>::new()];
+    let mut raw_locations = vec![Vec::<(Tag, i32)>::new()];
 
     while let Some(stops) = stop_lists.pop() {
         let mut new_locations = Vec::new();
@@ -98,7 +133,7 @@ fn constellation(font: &FontRef<'_>) -> HashSet<Location> {
         for location in raw_locations.iter() {
             for (tag, pos) in stops.iter() {
                 let mut location = location.clone();
-                location.push((tag.clone(), *pos as f32));
+                location.push((tag.clone(), *pos));
                 new_locations.push(location);
             }
         }
@@ -110,10 +145,99 @@ fn constellation(font: &FontRef<'_>) -> HashSet<Location> {
 
     raw_locations
         .into_iter()
-        .map(|l| font_axes.location(&l))
+        .map(|user_coords| {
+            let design = user_coords
+                .iter()
+                .map(|(tag, value)| (tag.clone(), *value as f32))
+                .collect::<Vec<_>>();
+            (font_axes.location(&design), user_coords)
+        })
+        .collect()
+}
+
+/// Locations taken from the font's `fvar` named instances instead of the Cartesian product
+/// of axis stops, so comparisons can target exactly the shipped design instances.
+fn named_instances(font: &FontRef<'_>) -> Vec<(Location, Vec<(Tag, i32)>)> {
+    let fvar = font.fvar().unwrap();
+    let axis_tags = fvar
+        .axes()
+        .unwrap()
+        .iter()
+        .map(|axis| axis.axis_tag())
+        .collect::<Vec<_>>();
+    let font_axes = font.axes();
+
+    fvar.instances()
+        .unwrap()
+        .iter()
+        .filter_map(|instance| instance.ok())
+        .map(|instance| {
+            let user_coords = axis_tags
+                .iter()
+                .cloned()
+                .zip(instance.coordinates.iter().map(|c| c.get().to_i32()))
+                .collect::<Vec<_>>();
+            let design = user_coords
+                .iter()
+                .map(|(tag, value)| (tag.clone(), *value as f32))
+                .collect::<Vec<_>>();
+            (font_axes.location(&design), user_coords)
+        })
+        .collect()
+}
+
+/// Parse a `--axis TAG:STEP` argument into an axis tag and its sample step, in the axis's user coordinates.
+fn parse_axis_override(raw: &str) -> (Tag, i32) {
+    let (tag, step) = raw
+        .split_once(':')
+        .unwrap_or_else(|| panic!("Invalid --axis {raw:?}, expected TAG:STEP"));
+    let step: i32 = step
+        .parse()
+        .unwrap_or_else(|e| panic!("Invalid step in --axis {raw:?}: {e}"));
+    if step <= 0 {
+        panic!("Invalid step in --axis {raw:?}: step must be > 0, got {step}");
+    }
+    (parse_tag(tag), step)
+}
+
+fn parse_tag(raw: &str) -> Tag {
+    let bytes = raw.as_bytes();
+    if bytes.is_empty() || bytes.len() > 4 {
+        panic!("Invalid axis tag {raw:?}, expected 1-4 ASCII characters");
+    }
+    let mut padded = [b' '; 4];
+    padded[..bytes.len()].copy_from_slice(bytes);
+    Tag::new(&padded)
+}
+
+/// The axis tag/value pairs (in user units) that produced `loc`, sorted by tag for
+/// deterministic output.
+fn location_axes(
+    loc: &Location,
+    labels: &HashMap<Location, Vec<(Tag, i32)>>,
+) -> Vec<(String, i32)> {
+    let Some(coords) = labels.get(loc) else {
+        return Vec::new();
+    };
+    let mut coords = coords.clone();
+    coords.sort_by_key(|(tag, _)| tag.to_string());
+    coords
+        .into_iter()
+        .map(|(tag, value)| (tag.to_string(), value))
         .collect()
 }
 
+fn describe_location(loc: &Location, labels: &HashMap<Location, Vec<(Tag, i32)>>) -> String {
+    let axes = location_axes(loc, labels);
+    if axes.is_empty() {
+        return format!("{loc:?}");
+    }
+    axes.into_iter()
+        .map(|(tag, value)| format!("{tag}={value}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 fn subpaths(icon_name: &str, path: &str) -> Vec<BezPath> {
     path.chars()
         .enumerate()
@@ -142,7 +266,228 @@ fn parse_path(svg: &str) -> (&str, &str, &str) {
     (preamble, path, suffix)
 }
 
-fn equivalent_paths(icon_name: &str, left_svg: &str, right_svg: &str) -> bool {
+/// How finely curves are flattened to polylines before measuring distance, in font units.
+const FLATTEN_ACCURACY: f64 = 0.1;
+
+fn flatten_subpath(subpath: &BezPath, accuracy: f64) -> Vec<Point> {
+    let mut points = Vec::new();
+    kurbo::flatten(subpath, accuracy, |el| match el {
+        PathEl::MoveTo(p) | PathEl::LineTo(p) => points.push(p),
+        PathEl::ClosePath => {}
+        _ => unreachable!("flatten only ever emits move/line/close"),
+    });
+    points
+}
+
+fn point_to_polyline_dist(p: Point, poly: &[Point]) -> f64 {
+    if poly.len() < 2 {
+        return poly
+            .iter()
+            .map(|q| p.distance(*q))
+            .fold(f64::INFINITY, f64::min);
+    }
+    poly.windows(2)
+        .map(|w| Line::new(w[0], w[1]).nearest(p, 1e-6).distance_sq)
+        .fold(f64::INFINITY, f64::min)
+        .sqrt()
+}
+
+/// Discrete directed Hausdorff distance: the worst-case distance from a vertex of `a` to `b`.
+fn directed_hausdorff(a: &[Point], b: &[Point]) -> f64 {
+    a.iter()
+        .map(|p| point_to_polyline_dist(*p, b))
+        .fold(0.0, f64::max)
+}
+
+fn symmetric_hausdorff(a: &[Point], b: &[Point]) -> f64 {
+    directed_hausdorff(a, b).max(directed_hausdorff(b, a))
+}
+
+fn centroid(points: &[Point]) -> Point {
+    let n = points.len().max(1) as f64;
+    (points
+        .iter()
+        .fold(Point::ZERO.to_vec2(), |acc, p| acc + p.to_vec2())
+        / n)
+        .to_point()
+}
+
+/// All cyclic rotations of `poly`, in both winding directions, each re-closed so the
+/// closing edge is part of the polyline.
+fn polyline_variants(poly: &[Point]) -> Vec<Vec<Point>> {
+    let mut open = poly.to_vec();
+    if open.len() > 1 && open.first() == open.last() {
+        open.pop();
+    }
+    let n = open.len();
+    if n == 0 {
+        return vec![poly.to_vec()];
+    }
+
+    let mut variants = Vec::new();
+    for rotation in 0..n {
+        for reversed in [false, true] {
+            let mut rotated: Vec<Point> = open
+                .iter()
+                .cycle()
+                .skip(rotation)
+                .take(n)
+                .cloned()
+                .collect();
+            if reversed {
+                rotated.reverse();
+            }
+            rotated.push(rotated[0]);
+            variants.push(rotated);
+        }
+    }
+    variants
+}
+
+/// Above this subpath count the bitmask-DP assignment solve below stops being cheap (its
+/// state space and memory are both O(2^n)), so icons with more subpaths than this fall back
+/// to a greedy nearest-centroid match instead of risking a multi-gigabyte allocation or, past
+/// `n == 64`, an overflowing shift. Grids/QR-style glyphs and dialpads are the icons most
+/// likely to have enough disjoint subpaths to hit this.
+const MAX_EXACT_MATCH_SUBPATHS: usize = 12;
+
+/// Pair up subpaths by centroid distance rather than assuming matching index order, so
+/// subpaths reordered between the two fonts still compare against their true counterpart.
+/// Up to [`MAX_EXACT_MATCH_SUBPATHS`] this is a minimum-cost bipartite matching (over the sum
+/// of centroid distances), not a greedy nearest-first assignment, so one subpath grabbing the
+/// closest centroid can't force a worse pairing onto the rest; above that threshold it falls
+/// back to greedy nearest-first. `lefts` and `rights` must be the same length; callers already
+/// bail out on a subpath-count mismatch before reaching this function.
+fn match_by_centroid(lefts: &[Vec<Point>], rights: &[Vec<Point>]) -> Vec<(usize, usize)> {
+    let n = lefts.len();
+    if n == 0 || n != rights.len() {
+        return Vec::new();
+    }
+
+    let left_centroids = lefts.iter().map(|l| centroid(l)).collect::<Vec<_>>();
+    let right_centroids = rights.iter().map(|r| centroid(r)).collect::<Vec<_>>();
+    let cost = left_centroids
+        .iter()
+        .map(|lc| {
+            right_centroids
+                .iter()
+                .map(|rc| lc.distance(*rc))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    if n > MAX_EXACT_MATCH_SUBPATHS {
+        return greedy_match_by_cost(&cost);
+    }
+
+    // Assignment problem via bitmask DP: dp[mask] is the minimum cost of matching the first
+    // popcount(mask) lefts (in order) to the rights in `mask`.
+    let full = 1usize << n;
+    let mut dp = vec![f64::INFINITY; full];
+    let mut choice = vec![usize::MAX; full];
+    dp[0] = 0.0;
+    for mask in 0..full {
+        let k = mask.count_ones() as usize;
+        if k >= n || !dp[mask].is_finite() {
+            continue;
+        }
+        for (j, &c) in cost[k].iter().enumerate() {
+            if mask & (1 << j) != 0 {
+                continue;
+            }
+            let next = mask | (1 << j);
+            let candidate = dp[mask] + c;
+            if candidate < dp[next] {
+                dp[next] = candidate;
+                choice[next] = j;
+            }
+        }
+    }
+
+    let mut pairs = vec![(0, 0); n];
+    let mut mask = full - 1;
+    for li in (0..n).rev() {
+        let ri = choice[mask];
+        pairs[li] = (li, ri);
+        mask &= !(1 << ri);
+    }
+    pairs
+}
+
+/// Greedy nearest-first fallback for [`match_by_centroid`] above [`MAX_EXACT_MATCH_SUBPATHS`]:
+/// process lefts in order, each grabbing its cheapest still-unused right. Not optimal, but
+/// O(n^2) in time and memory instead of O(2^n).
+fn greedy_match_by_cost(cost: &[Vec<f64>]) -> Vec<(usize, usize)> {
+    let n = cost.len();
+    let mut used_right = vec![false; n];
+    let mut pairs = Vec::new();
+    for (li, costs) in cost.iter().enumerate() {
+        let nearest = costs
+            .iter()
+            .enumerate()
+            .filter(|(ri, _)| !used_right[*ri])
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .map(|(ri, _)| ri);
+        let Some(ri) = nearest else {
+            continue;
+        };
+        used_right[ri] = true;
+        pairs.push((li, ri));
+    }
+    pairs
+}
+
+fn exactly_equivalent_subpaths(left_subpath: BezPath, right_subpath: BezPath) -> bool {
+    if left_subpath == right_subpath {
+        return true;
+    }
+    if left_subpath.is_empty() {
+        return false;
+    }
+    // Sometimes one is rotated
+    let left_elements = left_subpath.into_elements();
+    let mut right_elements = right_subpath.into_elements();
+    right_elements.rotate_right(1);
+
+    left_elements == right_elements
+}
+
+/// Geometric equivalence: flatten each subpath to a polyline and accept it if any
+/// rotation/orientation of the left polyline comes within `tolerance` font units of the
+/// right one under a symmetric Hausdorff distance. Subpaths are paired by nearest centroid
+/// so reordered subpaths still line up.
+fn fuzzily_equivalent_subpaths(
+    left_subpaths: &[BezPath],
+    right_subpaths: &[BezPath],
+    tolerance: f64,
+) -> bool {
+    let left_polys = left_subpaths
+        .iter()
+        .map(|p| flatten_subpath(p, FLATTEN_ACCURACY))
+        .collect::<Vec<_>>();
+    let right_polys = right_subpaths
+        .iter()
+        .map(|p| flatten_subpath(p, FLATTEN_ACCURACY))
+        .collect::<Vec<_>>();
+
+    let pairs = match_by_centroid(&left_polys, &right_polys);
+    if pairs.len() != left_polys.len() {
+        return false;
+    }
+
+    pairs.into_iter().all(|(li, ri)| {
+        polyline_variants(&left_polys[li])
+            .into_iter()
+            .any(|variant| symmetric_hausdorff(&variant, &right_polys[ri]) <= tolerance)
+    })
+}
+
+fn equivalent_paths(
+    icon_name: &str,
+    left_svg: &str,
+    right_svg: &str,
+    tolerance: Option<f64>,
+) -> bool {
     let left_path = parse_path(left_svg).1;
     let right_path = parse_path(right_svg).1;
 
@@ -153,23 +498,190 @@ fn equivalent_paths(icon_name: &str, left_svg: &str, right_svg: &str) -> bool {
         return false;
     }
 
-    left_subpaths
-        .into_iter()
-        .zip(right_subpaths.into_iter())
-        .all(|(left_subpath, right_subpath)| {
-            if left_subpath == right_subpath {
-                return true;
-            }
-            if left_subpath.is_empty() {
-                return false;
-            }
-            // Sometimes one is rotated
-            let left_elements = left_subpath.into_elements();
-            let mut right_elements = right_subpath.into_elements();
-            right_elements.rotate_right(1);
+    let Some(tolerance) = tolerance else {
+        return left_subpaths.into_iter().zip(right_subpaths).all(
+            |(left_subpath, right_subpath)| {
+                exactly_equivalent_subpaths(left_subpath, right_subpath)
+            },
+        );
+    };
+
+    fuzzily_equivalent_subpaths(&left_subpaths, &right_subpaths, tolerance)
+}
+
+/// Best-case symmetric Hausdorff distance between `left_svg` and `right_svg`, for display in
+/// a report. `f64::INFINITY` when the subpath counts don't even match.
+fn path_distance(icon_name: &str, left_svg: &str, right_svg: &str) -> f64 {
+    let left_path = parse_path(left_svg).1;
+    let right_path = parse_path(right_svg).1;
+
+    let left_subpaths = subpaths(icon_name, left_path);
+    let right_subpaths = subpaths(icon_name, right_path);
+
+    if left_subpaths.len() != right_subpaths.len() {
+        return f64::INFINITY;
+    }
+
+    let left_polys = left_subpaths
+        .iter()
+        .map(|p| flatten_subpath(p, FLATTEN_ACCURACY))
+        .collect::<Vec<_>>();
+    let right_polys = right_subpaths
+        .iter()
+        .map(|p| flatten_subpath(p, FLATTEN_ACCURACY))
+        .collect::<Vec<_>>();
 
-            left_elements == right_elements
+    match_by_centroid(&left_polys, &right_polys)
+        .into_iter()
+        .map(|(li, ri)| {
+            polyline_variants(&left_polys[li])
+                .into_iter()
+                .map(|variant| symmetric_hausdorff(&variant, &right_polys[ri]))
+                .fold(f64::INFINITY, f64::min)
         })
+        .fold(0.0, f64::max)
+}
+
+/// Everything an HTML report row needs to render one failing (icon, location) pair.
+struct ReportEntry {
+    icon: String,
+    location: String,
+    left_svg: String,
+    right_svg: String,
+    distance: f64,
+}
+
+/// Per-icon pass/fail tally for the report's summary table.
+struct IconSummary {
+    icon: String,
+    failing_locations: usize,
+    total_locations: usize,
+    failing: Vec<Location>,
+}
+
+#[derive(Serialize)]
+struct JsonLocation {
+    axes: Vec<(String, i32)>,
+}
+
+#[derive(Serialize)]
+struct JsonIconResult {
+    icon: String,
+    status: &'static str,
+    failing_locations: Vec<JsonLocation>,
+    total_locations: usize,
+}
+
+#[derive(Serialize)]
+struct JsonResults {
+    inconsistent_locations: bool,
+    total_locations_tested: usize,
+    only_left: Vec<String>,
+    only_right: Vec<String>,
+    icons: Vec<JsonIconResult>,
+}
+
+/// Escape the characters that would otherwise break an HTML attribute or inject markup, for
+/// untrusted names/labels spliced into the report (icon names and fvar instance/axis labels
+/// are attacker-controlled input, since this tool diffs arbitrary fonts). Not meant for the
+/// `left_svg`/`right_svg` markup, which is rendered as SVG on purpose.
+fn escape_html(raw: &str) -> String {
+    raw.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn svg_open_close(svg: &str) -> (&str, &str) {
+    let (preamble, _, suffix) = parse_path(svg);
+    let path_start = preamble.rfind("<path").unwrap();
+    let tag_end = suffix.find('>').map(|i| i + 1).unwrap_or(suffix.len());
+    (&preamble[..path_start], &suffix[tag_end..])
+}
+
+fn overlay_svg(left_svg: &str, right_svg: &str) -> String {
+    let (svg_open, svg_close) = svg_open_close(left_svg);
+    let left_path = parse_path(left_svg).1;
+    let right_path = parse_path(right_svg).1;
+    format!(
+        "{svg_open}<path d=\"{left_path}\" fill=\"#e03131\" fill-opacity=\"0.5\"/>\
+         <path d=\"{right_path}\" fill=\"#1971c2\" fill-opacity=\"0.5\"/>{svg_close}"
+    )
+}
+
+fn write_report(
+    dir: &str,
+    only_left: &[Icon],
+    only_right: &[Icon],
+    icon_summaries: &[IconSummary],
+    entries: &[ReportEntry],
+) {
+    fs::create_dir_all(dir).unwrap_or_else(|e| panic!("Unable to create {dir}: {e}"));
+
+    let mut html = String::new();
+    html += "<!doctype html><html><head><meta charset=\"utf-8\">";
+    html += "<title>compare_icon_fonts report</title><style>";
+    html += "body{font-family:sans-serif}table{border-collapse:collapse}";
+    html += "td,th{border:1px solid #ccc;padding:4px 8px;text-align:left}";
+    html += "svg{width:96px;height:96px;background:#fafafa}";
+    html += ".pass{color:#2b8a3e}.fail{color:#c92a2a}</style></head><body>";
+    html += "<h1>compare_icon_fonts report</h1>";
+
+    html +=
+        "<h2>Summary</h2><table><tr><th>Icon</th><th>Status</th><th>Failing locations</th></tr>";
+    for summary in icon_summaries {
+        let icon = escape_html(&summary.icon);
+        if summary.failing_locations > 0 {
+            html += &format!(
+                "<tr><td><a href=\"#{icon}\">{icon}</a></td><td class=\"fail\">fail</td><td>{bad}/{total}</td></tr>",
+                bad = summary.failing_locations,
+                total = summary.total_locations,
+            );
+        } else {
+            html += &format!(
+                "<tr><td>{icon}</td><td class=\"pass\">pass</td><td>0/{total}</td></tr>",
+                total = summary.total_locations,
+            );
+        }
+    }
+    html += "</table>";
+
+    html += &format!(
+        "<h3>Only in left ({})</h3><p>{}</p>",
+        only_left.len(),
+        only_left
+            .iter()
+            .map(|i| escape_html(&i.names.join(",")))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    html += &format!(
+        "<h3>Only in right ({})</h3><p>{}</p>",
+        only_right.len(),
+        only_right
+            .iter()
+            .map(|i| escape_html(&i.names.join(",")))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    html += "<h2>Failures</h2>";
+    html += "<table><tr><th>Icon</th><th>Location</th><th>Left</th><th>Right</th><th>Overlay</th><th>Distance</th></tr>";
+    for entry in entries {
+        html += &format!(
+            "<tr id=\"{icon}\"><td>{icon}</td><td>{location}</td><td>{left}</td><td>{right}</td><td>{overlay}</td><td>{distance:.3}</td></tr>",
+            icon = escape_html(&entry.icon),
+            location = escape_html(&entry.location),
+            left = entry.left_svg,
+            right = entry.right_svg,
+            overlay = overlay_svg(&entry.left_svg, &entry.right_svg),
+            distance = entry.distance,
+        );
+    }
+    html += "</table></body></html>";
+
+    let path = Path::new(dir).join("index.html");
+    fs::write(&path, html).unwrap_or_else(|e| panic!("Unable to write {}: {e}", path.display()));
 }
 
 fn save_failure(icon_name: &str, side: &str, content: &str, nth: usize) {
@@ -208,6 +720,76 @@ fn save_failure(icon_name: &str, side: &str, content: &str, nth: usize) {
     fs::write(&path, segments).unwrap_or_else(|e| panic!("Unable to write {path}: {e}"));
 }
 
+/// Draw `icon` at every location in `test_locs` in both fonts and compare them, returning
+/// this icon's pass/fail summary plus any report rows. Pure with respect to shared state
+/// (aside from `save_failure`'s writes to distinct files) so it's safe to call from multiple
+/// threads at once, one `Icon` per call.
+fn compare_icon(
+    icon: &Icon,
+    refs: &[FontRef<'_>],
+    test_locs: &HashSet<&Location>,
+    location_labels: &HashMap<Location, Vec<(Tag, i32)>>,
+    upem: u16,
+    tolerance: Option<f64>,
+    want_report: bool,
+) -> (IconSummary, Vec<ReportEntry>) {
+    let mut bad_locs = Vec::new();
+    let mut good_locs = Vec::new();
+    let mut report_entries = Vec::new();
+
+    for loc in test_locs.iter() {
+        let draw_opts = DrawOptions::new(
+            IconIdentifier::Name(icon.names[0].as_str().into()),
+            upem.into(),
+            (*loc).into(),
+            SvgPathStyle::Unchanged(0),
+        );
+        let mut svgs = Vec::new();
+        for font_ref in refs.iter() {
+            svgs.push(
+                draw_icon(font_ref, &draw_opts)
+                    .unwrap_or_else(|e| panic!("Unable to draw {icon:?} at {loc:?}: {e}")),
+            );
+        }
+        let [left_svg, right_svg] = svgs.as_slice() else {
+            unreachable!("??");
+        };
+        if !equivalent_paths(icon.names[0].as_str(), left_svg, right_svg, tolerance) {
+            if want_report {
+                report_entries.push(ReportEntry {
+                    icon: icon.names[0].clone(),
+                    location: describe_location(*loc, location_labels),
+                    left_svg: left_svg.clone(),
+                    right_svg: right_svg.clone(),
+                    distance: path_distance(icon.names[0].as_str(), left_svg, right_svg),
+                });
+            } else {
+                save_failure(icon.names[0].as_str(), "left", left_svg, bad_locs.len());
+                save_failure(icon.names[0].as_str(), "right", right_svg, bad_locs.len());
+            }
+
+            bad_locs.push(loc);
+        } else {
+            good_locs.push(loc);
+        }
+    }
+
+    let summary = IconSummary {
+        icon: icon.names[0].clone(),
+        failing_locations: bad_locs.len(),
+        total_locations: test_locs.len(),
+        failing: bad_locs.iter().map(|loc| (**loc).clone()).collect(),
+    };
+    (summary, report_entries)
+}
+
+/// Output mode: free-form text for humans, or a single structured JSON document for CI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
@@ -215,6 +797,38 @@ struct Args {
     #[arg(short, long, default_value = None)]
     filter: Option<String>,
 
+    /// Maximum geometric distance, in font units, allowed between two paths before they're
+    /// considered different shapes. When unset paths must match structurally (same
+    /// element sequence, up to one rotation).
+    #[arg(long)]
+    tolerance: Option<f64>,
+
+    /// Directory to write a self-contained HTML visual-diff report to. When set, failures
+    /// are rendered there instead of being dumped to /tmp.
+    #[arg(long)]
+    report: Option<String>,
+
+    /// Output format. `json` emits a single structured document instead of free-form text,
+    /// for consumption by CI pipelines.
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Override the sample step for an axis, as TAG:STEP in the axis's user coordinates (e.g. `wght:100`).
+    /// Repeatable. Axes without a matching known default or override fall back to an even
+    /// N-way split between min and max instead of panicking.
+    #[arg(long = "axis")]
+    axis: Vec<String>,
+
+    /// Compare only the font's named fvar instances instead of the full Cartesian product
+    /// of axis stops.
+    #[arg(long)]
+    named_instances: bool,
+
+    /// Number of worker threads to compare icons with. Defaults to rayon's usual choice
+    /// (one per available core).
+    #[arg(long)]
+    jobs: Option<usize>,
+
     /// Number of times to greet
     #[arg(num_args = 2)]
     paths: Vec<String>,
@@ -276,13 +890,39 @@ fn main() -> ExitCode {
         );
     }
 
-    let constellations = refs.iter().map(constellation).collect::<Vec<_>>();
+    let axis_overrides = args
+        .axis
+        .iter()
+        .map(|raw| parse_axis_override(raw))
+        .collect::<HashMap<_, _>>();
+
+    let constellations = refs
+        .iter()
+        .map(|font| {
+            if args.named_instances {
+                named_instances(font)
+            } else {
+                constellation(font, &axis_overrides)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let location_labels = constellations
+        .iter()
+        .flatten()
+        .map(|(loc, coords)| (loc.clone(), coords.clone()))
+        .collect::<HashMap<_, _>>();
+    let location_sets = constellations
+        .iter()
+        .map(|c| c.iter().map(|(loc, _)| loc.clone()).collect::<HashSet<_>>())
+        .collect::<Vec<_>>();
 
-    let [left_locs, right_locs] = constellations.as_slice() else {
+    let [left_locs, right_locs] = location_sets.as_slice() else {
         unreachable!("Eh?");
     };
 
-    if left_locs != right_locs {
+    let inconsistent_locations = left_locs != right_locs;
+    if inconsistent_locations && args.format == Format::Text {
         println!("Inconsistent location sets, did axes or ranges of axes change?");
     }
     let test_locs = left_locs.intersection(right_locs).collect::<HashSet<_>>();
@@ -293,66 +933,333 @@ fn main() -> ExitCode {
     let mut test_icons = left_icons.intersection(right_icons).collect::<Vec<_>>();
     test_icons.sort_by_cached_key(|i| i.names.join(","));
 
-    println!(
-        "Testing {} icons at {} locations...",
-        test_icons.len(),
-        test_locs.len()
-    );
+    if args.format == Format::Text {
+        println!(
+            "Testing {} icons at {} locations...",
+            test_icons.len(),
+            test_locs.len()
+        );
+    }
 
-    let mut errs = 0;
+    let only_left = left_icons.only_in(right_icons);
+    let only_right = right_icons.only_in(left_icons);
+    if args.format == Format::Text {
+        print_problems("only_left", &only_left);
+        print_problems("only_right", &only_right);
+    }
+    let mut errs = only_left.len() + only_right.len();
 
-    errs += left_icons.print_only("only_left", &right_icons);
-    errs += right_icons.print_only("only_right", &left_icons);
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()
+            .unwrap_or_else(|e| panic!("Unable to configure {jobs} worker threads: {e}"));
+    }
 
-    errs += test_icons
-        .iter()
+    // Each icon is independent, so compare them across a rayon worker pool. par_iter().map()
+    // preserves test_icons' order in the collected Vec regardless of completion order, so
+    // output stays deterministic.
+    let icon_results = test_icons
+        .par_iter()
         .map(|icon| {
-            let mut bad_locs = Vec::new();
-            let mut good_locs = Vec::new();
-            for loc in test_locs.iter() {
-                let draw_opts = DrawOptions::new(
-                    IconIdentifier::Name(icon.names[0].as_str().into()),
-                    upem.into(),
-                    (*loc).into(),
-                    SvgPathStyle::Unchanged(0),
-                );
-                let mut svgs = Vec::new();
-                for font_ref in refs.iter() {
-                    svgs.push(
-                        draw_icon(font_ref, &draw_opts)
-                            .unwrap_or_else(|e| panic!("Unable to draw {icon:?} at {loc:?}: {e}")),
-                    );
-                }
-                let [left_svg, right_svg] = svgs.as_slice() else {
-                    unreachable!("??");
-                };
-                if !equivalent_paths(icon.names[0].as_str(), left_svg, right_svg) {
-                    save_failure(icon.names[0].as_str(), "left", &left_svg, bad_locs.len());
-                    save_failure(icon.names[0].as_str(), "right", &right_svg, bad_locs.len());
-
-                    bad_locs.push(loc);
-                } else {
-                    good_locs.push(loc);
-                }
-            }
-            if !bad_locs.is_empty() {
+            compare_icon(
+                icon,
+                &refs,
+                &test_locs,
+                &location_labels,
+                upem,
+                args.tolerance,
+                args.report.is_some(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut icon_summaries = Vec::with_capacity(icon_results.len());
+    let mut report_entries = Vec::new();
+
+    for (summary, entries) in icon_results {
+        if args.format == Format::Text {
+            if summary.failing_locations > 0 {
                 println!(
                     "{} fails at {}/{} locations",
-                    icon.names[0],
-                    bad_locs.len(),
-                    test_locs.len()
+                    summary.icon, summary.failing_locations, summary.total_locations
                 );
             } else {
-                println!("{} passes", icon.names[0]);
+                println!("{} passes", summary.icon);
             }
-            bad_locs.len()
-        })
-        .sum::<usize>();
+        }
+        errs += summary.failing_locations;
+        report_entries.extend(entries);
+        icon_summaries.push(summary);
+    }
+
+    if let Some(report_dir) = args.report.as_deref() {
+        write_report(
+            report_dir,
+            &only_left,
+            &only_right,
+            &icon_summaries,
+            &report_entries,
+        );
+        if args.format == Format::Text {
+            println!("Wrote report to {report_dir}/index.html");
+        }
+    }
+
+    match args.format {
+        Format::Text => {
+            if errs > 0 {
+                println!("Eeek, {errs} failures!");
+            }
+        }
+        Format::Json => {
+            let results = JsonResults {
+                inconsistent_locations,
+                total_locations_tested: test_locs.len(),
+                only_left: only_left.iter().map(|i| i.names.join(",")).collect(),
+                only_right: only_right.iter().map(|i| i.names.join(",")).collect(),
+                icons: icon_summaries
+                    .into_iter()
+                    .map(|summary| JsonIconResult {
+                        icon: summary.icon,
+                        status: if summary.failing_locations == 0 {
+                            "pass"
+                        } else {
+                            "fail"
+                        },
+                        failing_locations: summary
+                            .failing
+                            .iter()
+                            .map(|loc| JsonLocation {
+                                axes: location_axes(loc, &location_labels),
+                            })
+                            .collect(),
+                        total_locations: summary.total_locations,
+                    })
+                    .collect(),
+            };
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&results)
+                    .unwrap_or_else(|e| panic!("Unable to serialize results: {e}"))
+            );
+        }
+    }
 
     if errs == 0 {
         ExitCode::SUCCESS
     } else {
-        println!("Eeek, {errs} failures!");
         ExitCode::FAILURE
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_includes_min_default_max_and_every_step() {
+        assert_eq!(stops(0, 50, 100, 25), vec![0, 25, 50, 75, 100, 50, 100]);
+    }
+
+    #[test]
+    fn stops_handles_min_eq_max() {
+        assert_eq!(stops(400, 400, 400, 50), vec![400, 400, 400]);
+    }
+
+    #[test]
+    fn even_split_handles_min_eq_max() {
+        assert_eq!(even_split(400, 400, 400), vec![400, 400, 400]);
+    }
+
+    #[test]
+    fn even_split_covers_full_range() {
+        assert_eq!(even_split(0, 400, 1000), vec![0, 400, 1000, 250, 500, 750]);
+    }
+
+    #[test]
+    fn even_split_spaces_uneven_ranges_uniformly() {
+        // 10 doesn't divide evenly by UNKNOWN_AXIS_SPLITS (4); truncating integer division
+        // used to leave gaps of 2,2,2,4 instead of a roughly even 2.5 each.
+        assert_eq!(even_split(0, 5, 10), vec![0, 5, 10, 3, 5, 8]);
+    }
+
+    #[test]
+    fn parse_axis_override_accepts_positive_step() {
+        assert_eq!(parse_axis_override("wght:200"), (Tag::new(b"wght"), 200));
+    }
+
+    #[test]
+    #[should_panic(expected = "step must be > 0")]
+    fn parse_axis_override_rejects_zero_step() {
+        parse_axis_override("wght:0");
+    }
+
+    #[test]
+    #[should_panic(expected = "step must be > 0")]
+    fn parse_axis_override_rejects_negative_step() {
+        parse_axis_override("wght:-10");
+    }
+
+    #[test]
+    fn directed_hausdorff_is_zero_for_identical_polylines() {
+        let poly = vec![
+            Point::new(0.0, 0.0),
+            Point::new(10.0, 0.0),
+            Point::new(10.0, 10.0),
+        ];
+        assert_eq!(directed_hausdorff(&poly, &poly), 0.0);
+    }
+
+    #[test]
+    fn directed_hausdorff_finds_worst_case_vertex() {
+        // Both endpoints of `a` project perpendicularly onto `b`, 3 units below it.
+        let a = vec![Point::new(0.0, 0.0), Point::new(10.0, 0.0)];
+        let b = vec![Point::new(0.0, 3.0), Point::new(10.0, 3.0)];
+        assert_eq!(directed_hausdorff(&a, &b), 3.0);
+    }
+
+    #[test]
+    fn symmetric_hausdorff_is_symmetric() {
+        let a = vec![Point::new(0.0, 0.0), Point::new(10.0, 0.0)];
+        let b = vec![Point::new(0.0, 3.0), Point::new(10.0, 3.0)];
+        assert_eq!(symmetric_hausdorff(&a, &b), symmetric_hausdorff(&b, &a));
+    }
+
+    #[test]
+    fn polyline_variants_includes_rotations_and_reflection() {
+        let square = vec![
+            Point::new(0.0, 0.0),
+            Point::new(10.0, 0.0),
+            Point::new(10.0, 10.0),
+            Point::new(0.0, 10.0),
+            Point::new(0.0, 0.0),
+        ];
+        let rotated = vec![
+            Point::new(10.0, 0.0),
+            Point::new(10.0, 10.0),
+            Point::new(0.0, 10.0),
+            Point::new(0.0, 0.0),
+            Point::new(10.0, 0.0),
+        ];
+
+        let variants = polyline_variants(&square);
+        assert_eq!(variants.len(), 8); // 4 rotations x 2 winding directions
+        assert!(variants.iter().all(|v| v.first() == v.last()));
+        assert!(variants.contains(&rotated));
+    }
+
+    #[test]
+    fn match_by_centroid_finds_globally_optimal_assignment() {
+        // right0 = (0,0), right1 = (3,0). left0 = (1,0) is closer to right0 (dist 1) than to
+        // right1 (dist 2), so a greedy nearest-first pass locks left0 onto right0 first and is
+        // forced into pairing left1 with right1 at distance 3, for a total cost of 4. But left1
+        // sits near right0 (dist 1) and far from right1 (dist 3), so the globally cheapest
+        // assignment swaps both pairs for a total cost of 1 + 2 = 3.
+        let right0 = vec![Point::new(0.0, 0.0)];
+        let right1 = vec![Point::new(3.0, 0.0)];
+        let left0 = vec![Point::new(1.0, 0.0)];
+        let left1 = vec![Point::new(1.0 / 6.0, 35f64.sqrt() / 6.0)];
+
+        let pairs = match_by_centroid(&[left0, left1], &[right0, right1]);
+        assert_eq!(pairs, vec![(0, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn match_by_centroid_rejects_mismatched_lengths() {
+        let lefts = vec![vec![Point::new(0.0, 0.0)]];
+        let rights = vec![vec![Point::new(0.0, 0.0)], vec![Point::new(5.0, 5.0)]];
+        assert!(match_by_centroid(&lefts, &rights).is_empty());
+    }
+
+    fn square_icon(offset_x: f64) -> String {
+        format!(
+            "<path d=\"M{x} 0L{x10} 0L{x10} 10L{x} 10ZM20 20L30 20L30 30ZM{x} 0\"/>",
+            x = offset_x,
+            x10 = offset_x + 10.0,
+        )
+    }
+
+    #[test]
+    fn equivalent_paths_accepts_identical_svgs_without_tolerance() {
+        let svg = square_icon(0.0);
+        assert!(equivalent_paths("test", &svg, &svg, None));
+    }
+
+    #[test]
+    fn equivalent_paths_rejects_mismatched_subpath_counts() {
+        let left = "<path d=\"M0 0L10 0L10 10ZM0 0\"/>";
+        let right = "<path d=\"M0 0L10 0L10 10ZM20 20L30 20L30 30ZM0 0\"/>";
+        assert!(!equivalent_paths("test", left, right, None));
+        assert!(!equivalent_paths("test", left, right, Some(1000.0)));
+    }
+
+    #[test]
+    fn equivalent_paths_tolerates_reordered_subpaths_under_tolerance() {
+        // Same two subpaths as the left icon, but written in the opposite order, which an
+        // index-order comparison would reject even though the icon is geometrically identical.
+        let left = "<path d=\"M0 0L10 0L10 10ZM20 20L30 20L30 30ZM0 0\"/>";
+        let right = "<path d=\"M20 20L30 20L30 30ZM0 0L10 0L10 10ZM20 20\"/>";
+        assert!(!equivalent_paths("test", left, right, None));
+        assert!(equivalent_paths("test", left, right, Some(0.01)));
+    }
+
+    #[test]
+    fn escape_html_escapes_special_characters() {
+        assert_eq!(
+            escape_html("<a>\"b\" & c"),
+            "&lt;a&gt;&quot;b&quot; &amp; c"
+        );
+    }
+
+    #[test]
+    fn svg_open_close_splits_around_the_path_element() {
+        let svg = "<svg foo=\"bar\"><path d=\"M0 0Z\"/></svg>";
+        assert_eq!(svg_open_close(svg), ("<svg foo=\"bar\">", "</svg>"));
+    }
+
+    #[test]
+    fn overlay_svg_wraps_both_paths_in_the_left_svgs_open_close() {
+        let left = "<svg foo=\"bar\"><path d=\"M0 0Z\"/></svg>";
+        let right = "<svg foo=\"bar\"><path d=\"M1 1Z\"/></svg>";
+        assert_eq!(
+            overlay_svg(left, right),
+            "<svg foo=\"bar\"><path d=\"M0 0Z\" fill=\"#e03131\" fill-opacity=\"0.5\"/>\
+             <path d=\"M1 1Z\" fill=\"#1971c2\" fill-opacity=\"0.5\"/></svg>"
+        );
+    }
+
+    #[test]
+    fn json_results_serializes_to_the_documented_shape() {
+        let results = JsonResults {
+            inconsistent_locations: true,
+            total_locations_tested: 4,
+            only_left: vec!["only_left_icon".to_string()],
+            only_right: vec![],
+            icons: vec![JsonIconResult {
+                icon: "star".to_string(),
+                status: "fail",
+                failing_locations: vec![JsonLocation {
+                    axes: vec![("wght".to_string(), 400)],
+                }],
+                total_locations: 4,
+            }],
+        };
+
+        assert_eq!(
+            serde_json::to_value(&results).unwrap(),
+            serde_json::json!({
+                "inconsistent_locations": true,
+                "total_locations_tested": 4,
+                "only_left": ["only_left_icon"],
+                "only_right": [],
+                "icons": [{
+                    "icon": "star",
+                    "status": "fail",
+                    "failing_locations": [{"axes": [["wght", 400]]}],
+                    "total_locations": 4,
+                }],
+            })
+        );
+    }
+}